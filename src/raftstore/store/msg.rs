@@ -0,0 +1,33 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use kvproto::metapb::Region;
+use raft::StateRole;
+
+/// Filter passed to `RegionInfoProvider::seek_region`/`seek_regions`. Returns whether `region`,
+/// currently in role `StateRole` on the local store, should be returned by the seek. Shared by
+/// every caller that builds a seek filter (GC, split-check, coprocessor, ...), so its arity is
+/// part of the public API and cannot change without updating all of them.
+pub type SeekRegionFilter = Box<Fn(&Region, StateRole) -> bool + Send>;
+
+/// Result of a `seek_region`/`seek_regions` call.
+#[derive(Debug, PartialEq)]
+pub enum SeekRegionResult {
+    /// A region matching the filter was found.
+    Found(Region),
+    /// No match was found within `limit` candidates; seeking again from `next_key` continues
+    /// where this call left off.
+    LimitExceeded { next_key: Vec<u8> },
+    /// No match was found before the end of the keyspace.
+    Ended,
+}
@@ -0,0 +1,71 @@
+// Copyright 2018 PingCAP, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::result;
+
+use kvproto::metapb::Region;
+
+use raftstore::store::msg::{SeekRegionFilter, SeekRegionResult};
+
+#[derive(Debug)]
+pub enum Error {
+    Other(Box<error::Error + Sync + Send>),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            Error::Other(ref e) => write!(f, "unknown error {:?}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        "engine error"
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// Read-only access to region placement metadata, backed by whatever component tracks it on the
+/// local store (currently `raftstore::coprocessor::RegionCollection`). Implementations are
+/// expected to serve these without blocking on the raftstore thread.
+pub trait RegionInfoProvider: Send + Sync {
+    /// Find the first region at or after `from` that passes `filter`, scanning at most `limit`
+    /// candidates.
+    fn seek_region(
+        &self,
+        from: &[u8],
+        filter: SeekRegionFilter,
+        limit: u32,
+    ) -> Result<SeekRegionResult>;
+
+    /// Batched form of `seek_region`: resolve every key in `from_keys` against the same filter and
+    /// limit, in one call.
+    fn seek_regions(
+        &self,
+        from_keys: Vec<Vec<u8>>,
+        filter: SeekRegionFilter,
+        limit: u32,
+    ) -> Result<Vec<SeekRegionResult>>;
+
+    /// Return every region whose `[start_key, end_key)` overlaps `[start, end)`, in key order. An
+    /// empty `end` means the range is unbounded to the right.
+    fn get_regions_in_range(&self, start: &[u8], end: &[u8]) -> Result<Vec<Region>>;
+
+    /// Return the single region whose `[start_key, end_key)` contains `key`, if any.
+    fn find_region_by_key(&self, key: &[u8]) -> Result<Option<Region>>;
+}